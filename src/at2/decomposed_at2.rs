@@ -3,18 +3,413 @@
 /// Deviations from AT2 as defined in the paper
 /// 1.  DONE: we decompose dependency tracking from the distributed algorithm
 /// 3.  TODO: we genaralize over the distributed algorithm
-/// 4.  TODO: seperate out resources from identity (a process id both identified an agent and an account) we generalize this so that
-use std::collections::{BTreeSet, HashMap, HashSet};
+/// 4.  DONE: seperate out resources from identity (a process id both identified an agent and an account) we generalize this so that
+///     one agent can own/act on several accounts, and Msg records the acting agent separately from the debited account.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::mem;
 
 use crdts::{CmRDT, Dot, VClock};
 
 use crate::at2::bank::{Account, Bank, Identity, Money, Transfer};
 
+/// A member not refreshed within this many of its own operation epochs is
+/// considered dead; its account is frozen for new debits.
+const MEMBERSHIP_EXPIRY_EPOCHS: u64 = 50;
+
+/// A bounded grant of debit authority: `grantee` may act on `account` up to
+/// whatever `limit` (and other caveats Bank enforces), without the account
+/// owner surrendering the whole account. Delegations are agent-scoped, not
+/// account-scoped, since Identity and Account are no longer the same thing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Delegation {
+    account: Account,
+    grantee: Identity,
+    limit: Money,
+}
+
+/// An agent's signed action: a transfer debiting `Msg::account`, or a grant
+/// of bounded debit authority over it. Both ride the same causal broadcast
+/// path, ordered per-agent by `Msg::source_version`, so a delegation is
+/// applied deterministically relative to the transfers around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Transfer(Transfer),
+    Delegate(Delegation),
+}
+
+/// A caveat narrows what a Capability authorizes. All caveats on a
+/// Capability must pass, and each is checked only against state every
+/// correct process has already applied (our own VClock knowledge, prior
+/// use counts), so all replicas agree on the outcome.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Caveat {
+    /// A single transfer may not exceed this amount.
+    MaxAmount(Money),
+    /// Invalid once the holder's causal knowledge of the minting account
+    /// passes this dot.
+    ExpiresAt(Dot<Identity>),
+    /// The transfer's recipient must be in this list.
+    RecipientAllowList(BTreeSet<Account>),
+    /// The capability may be spent at most this many times.
+    MaxUses(u64),
+}
+
+/// A restricted, composable spending right over `account`, minted by the
+/// account's owner and handed to `holder`. Modeled on capability/caveat
+/// systems where a reference is wrapped by caveats that attenuate what it
+/// authorizes: `holder` may attach this to a Msg instead of needing full
+/// ownership or a standing Delegation over `account`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Capability {
+    // The owner's causal dot at mint time; doubles as this capability's
+    // identity, so spend-count can be tracked deterministically across uses.
+    minted_at: Dot<Identity>,
+    account: Account,
+    holder: Identity,
+    caveats: Vec<Caveat>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct Msg {
-    op: Transfer,
+    // The account this op touches. Distinct from the signing agent, which
+    // is `source_version.actor` — an agent may hold delegated authority
+    // over an account it does not itself own.
+    account: Account,
+    op: Op,
     source_version: Dot<Identity>,
+    // Present when the signing agent is spending a Capability rather than acting
+    // with its own ownership of, or standing Delegation over, `account`.
+    capability: Option<Capability>,
+}
+
+/// A peer's membership advertisement. `version` is a process-local
+/// monotonically increasing counter (explicitly NOT a wall-clock
+/// timestamp, to avoid clock-skew problems), bumped on every refresh or
+/// on leave. Processes merge records by keeping the highest version per
+/// `id`, so membership forms a small CRDT map all correct processes
+/// converge on.
+///
+/// `epoch` is the advertiser's own `seq.dot(id)` count at advertise time —
+/// carried in the record itself, rather than recomputed by whoever merges
+/// it, so every correct replica that merges this exact record agrees on
+/// what epoch to stamp as its last-refreshed point.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PeerRecord {
+    id: Identity,
+    initial_balance: Money,
+    version: u64,
+    epoch: u64,
+    tombstone: bool,
+}
+
+/// A Proc-level state mutation queued during a turn, applied only when the
+/// turn commits. Proc's turn handlers queue these instead of touching
+/// `rec`/`seq`/the bank directly, so a turn's local effects and its outbound Cmds commit
+/// together, in the same place, rather than some running eagerly mid-turn
+/// and others waiting for turn end.
+#[derive(Debug, Clone)]
+enum Effect {
+    ApplyRec(Dot<Identity>),
+    ApplySeq(Dot<Identity>),
+    RecordCapUse(Dot<Identity>),
+    BankApply { account: Account, op: Op },
+    /// A validated op from `id` counts as liveness: refresh its membership
+    /// epoch to `epoch` (its own source_version counter) instead of relying
+    /// solely on explicit re-advertisement, so ordinary activity keeps an
+    /// active member from ever going stale.
+    RefreshLiveness { id: Identity, epoch: u64 },
+    /// Opens `id`'s account on first sight of its PeerRecord, so onboarding
+    /// commits alongside the rest of the turn's effects instead of racing
+    /// ahead of them.
+    OnboardAccount { id: Identity, initial_balance: Money },
+    /// Queues `msg` for validation once this turn commits, instead of
+    /// `accept` pushing straight onto `to_validate` mid-turn.
+    EnqueueForValidation { from: Identity, msg: Msg },
+}
+
+/// Accumulates one turn's effects — outbound Cmds, log lines, and queued
+/// state mutations from every entity that reacted to the turn's message —
+/// so they commit atomically at turn end rather than interleaving with
+/// mutations still in progress. Modeled on Syndicate's Activation/turn
+/// abstraction.
+#[derive(Debug, Default)]
+struct Turn {
+    cmds: Vec<Cmd>,
+    log_lines: Vec<String>,
+    effects: Vec<Effect>,
+}
+
+impl Turn {
+    fn new() -> Self {
+        Turn::default()
+    }
+
+    /// Queues an outbound Cmd for this turn.
+    fn send(&mut self, cmd: Cmd) {
+        self.cmds.push(cmd);
+    }
+
+    /// Queues a log line instead of printing immediately, so a turn's
+    /// output stays contiguous rather than interleaved with other turns'.
+    fn log(&mut self, line: String) {
+        self.log_lines.push(line);
+    }
+
+    /// Queues a state mutation to be applied once this turn's message has
+    /// finished being handled, instead of performing it inline.
+    fn defer(&mut self, effect: Effect) {
+        self.effects.push(effect);
+    }
+
+    /// Flushes this turn's queued log lines in order, then returns its
+    /// Cmds. Queued `effects` are not applied here: `Proc::turn` drains
+    /// and applies them before calling this.
+    fn commit(self) -> Vec<Cmd> {
+        for line in self.log_lines {
+            println!("{}", line);
+        }
+        self.cmds
+    }
+}
+
+/// A component that takes part in a turn: it reacts to `Self::Message` by
+/// updating its own state and queuing its outbound effects (log lines,
+/// Cmds) on `Turn` rather than performing them directly, and returns `Self::Outcome` so callers can react to what happened. It gets a
+/// chance to run follow-up work via `commit` once the turn's message has
+/// been handled, and teardown via `exit_hook` when a member leaves the
+/// membership.
+trait Entity {
+    type Message;
+    type Outcome;
+
+    fn message(&mut self, turn: &mut Turn, msg: Self::Message) -> Self::Outcome;
+
+    /// Runs once this turn's message has been handled, so an entity can
+    /// react to state other entities changed during the same turn.
+    fn commit(&mut self, turn: &mut Turn) {
+        let _ = turn;
+    }
+
+    /// Runs teardown for `id` when it leaves the membership, instead of
+    /// being spread across ad-hoc call sites.
+    fn exit_hook(&mut self, turn: &mut Turn, id: Identity) {
+        let _ = (turn, id);
+    }
+}
+
+/// What the Membership entity reacts to: a peer record observed, carrying
+/// the advertiser's own epoch rather than leaving the merging replica to
+/// recompute it locally.
+struct MembershipMsg {
+    record: PeerRecord,
+}
+
+/// Membership CRDT state and expiry policy, decoupled from VClock
+/// bookkeeping: the advertiser's epoch rides in `PeerRecord` itself, so
+/// this entity never needs to consult VClocks to decide what to stamp.
+#[derive(Debug, Default)]
+struct Membership {
+    records: HashMap<Identity, PeerRecord>,
+    refreshed_at_epoch: HashMap<Identity, u64>,
+    own_version: u64,
+}
+
+impl Membership {
+    /// Merges `record`, keeping the highest version per id (ties are a
+    /// no-op, so re-delivery is idempotent). Returns whether the merge
+    /// advanced our view.
+    fn merge(&mut self, turn: &mut Turn, record: PeerRecord) -> bool {
+        match self.records.get(&record.id) {
+            Some(existing) if existing.version >= record.version => false,
+            _ => {
+                // Only a record we actually accept gets to refresh the
+                // liveness clock — otherwise a stale or duplicate
+                // advertisement would reset it and defeat expiry. The
+                // epoch comes from the record itself, so every replica
+                // that accepts this exact record agrees on the value.
+                self.refreshed_at_epoch.insert(record.id, record.epoch);
+                turn.log(format!(
+                    "Merged membership record for {} at version {}",
+                    record.id, record.version
+                ));
+                self.records.insert(record.id, record);
+                true
+            }
+        }
+    }
+
+    /// Ordinary activity from `id` counts as liveness too: advances its
+    /// refreshed-at epoch to `epoch` (never backwards, so a replayed or
+    /// reordered refresh can't regress it) without requiring a fresh
+    /// `PeerRecord` advertisement for every op.
+    fn refresh_liveness(&mut self, id: Identity, epoch: u64) {
+        self.refreshed_at_epoch
+            .entry(id)
+            .and_modify(|e| *e = (*e).max(epoch))
+            .or_insert(epoch);
+    }
+
+    fn get(&self, id: Identity) -> Option<&PeerRecord> {
+        self.records.get(&id)
+    }
+
+    /// Whether `id` is a current (non-tombstoned) member.
+    fn is_member(&self, id: Identity) -> bool {
+        self.records.get(&id).map_or(false, |r| !r.tombstone)
+    }
+
+    /// Whether `id`'s membership record has gone stale: not refreshed
+    /// within MEMBERSHIP_EXPIRY_EPOCHS of its own operations.
+    fn is_expired(&self, id: Identity, current_epoch: u64) -> bool {
+        let refreshed_at = *self.refreshed_at_epoch.get(&id).unwrap_or(&0);
+        current_epoch.saturating_sub(refreshed_at) > MEMBERSHIP_EXPIRY_EPOCHS
+    }
+
+    fn next_version(&mut self) -> u64 {
+        self.own_version += 1;
+        self.own_version
+    }
+}
+
+impl Entity for Membership {
+    type Message = MembershipMsg;
+    /// Whether the merge advanced our view, so callers can decide whether
+    /// the change warrants onboarding or a reply broadcast.
+    type Outcome = bool;
+
+    fn message(&mut self, turn: &mut Turn, msg: MembershipMsg) -> bool {
+        self.merge(turn, msg.record)
+    }
+
+    fn exit_hook(&mut self, turn: &mut Turn, id: Identity) {
+        self.refreshed_at_epoch.remove(&id);
+        turn.log(format!("Membership torn down for {}", id));
+    }
+}
+
+/// What the AntiEntropy entity reacts to: a Msg delivered by `from`,
+/// together with the causal counter we expect next from that actor — our
+/// own `rec` knowledge at delivery time, supplied by the caller rather than
+/// this entity reading VClocks itself.
+struct AntiEntropyMsg {
+    from: Identity,
+    msg: Msg,
+    expected_counter: u64,
+}
+
+/// What handling an AntiEntropyMsg produced: the Msg became immediately
+/// deliverable, it was buffered behind a gap (with the missing range), or
+/// it was rejected outright (stale or duplicate).
+enum DeliveryOutcome {
+    Delivered(Msg),
+    Buffered { from_counter: u64, to_counter: u64 },
+    Rejected,
+}
+
+/// Append-only per-actor log of delivered Msgs, plus the out-of-order
+/// buffer for Msgs that arrived ahead of their causal predecessor. Lets a
+/// process serve another's anti-entropy request without re-deriving
+/// history from the bank, and repair gaps left by dropped or reordered
+/// broadcasts instead of losing the message.
+#[derive(Debug, Default)]
+struct AntiEntropy {
+    log: HashMap<Identity, BTreeMap<u64, Msg>>,
+    pending: HashMap<Identity, BTreeMap<u64, Msg>>,
+}
+
+impl AntiEntropy {
+    fn record(&mut self, from: Identity, msg: Msg) {
+        self.log
+            .entry(from)
+            .or_insert_with(BTreeMap::new)
+            .insert(msg.source_version.counter, msg);
+    }
+
+    /// Pops the buffered Msg for `from` at `counter`, if any — used to drain
+    /// `pending` once a gap closes.
+    fn take_pending(&mut self, from: Identity, counter: u64) -> Option<Msg> {
+        self.pending.get_mut(&from).and_then(|p| p.remove(&counter))
+    }
+
+    /// For each actor with buffered out-of-order messages, the counter
+    /// range of the gap blocking them: from the first counter past `rec`'s
+    /// contiguous received prefix, up to the highest counter we've buffered.
+    fn gaps(&self, rec: &VClock<Identity>) -> HashMap<Identity, (u64, u64)> {
+        self.pending
+            .iter()
+            .filter_map(|(actor, buffered)| {
+                let highest = *buffered.keys().next_back()?;
+                let from_counter = rec.dot(*actor).counter + 1;
+                Some((*actor, (from_counter, highest)))
+            })
+            .collect()
+    }
+
+    /// The logged Msgs for `actor` in the requested counter range, for
+    /// answering another process' anti-entropy request.
+    fn missing(&self, actor: Identity, from_counter: u64, to_counter: u64) -> Vec<Msg> {
+        match self.log.get(&actor) {
+            Some(log) => (from_counter..=to_counter)
+                .filter_map(|counter| log.get(&counter).cloned())
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+impl Entity for AntiEntropy {
+    type Message = AntiEntropyMsg;
+    type Outcome = DeliveryOutcome;
+
+    fn message(&mut self, turn: &mut Turn, msg: AntiEntropyMsg) -> DeliveryOutcome {
+        let AntiEntropyMsg {
+            from,
+            msg,
+            expected_counter,
+        } = msg;
+
+        if msg.source_version.counter == expected_counter {
+            turn.log(format!(
+                "Accepted message from {} and enqueued for validation",
+                from
+            ));
+            self.record(from, msg.clone());
+            DeliveryOutcome::Delivered(msg)
+        } else if msg.source_version.counter > expected_counter {
+            // Arrived ahead of its causal predecessor: buffer it instead of
+            // dropping it, and let the caller ask `from` to fill the gap.
+            turn.log(format!(
+                "Buffered out-of-order message from {}, missing counters {}..{}",
+                from, expected_counter, msg.source_version.counter
+            ));
+            self.pending
+                .entry(from)
+                .or_insert_with(BTreeMap::new)
+                .insert(msg.source_version.counter, msg.clone());
+            DeliveryOutcome::Buffered {
+                from_counter: expected_counter,
+                to_counter: msg.source_version.counter - 1,
+            }
+        } else {
+            turn.log(format!(
+                "Rejected message from {}, transfer source version is invalid: {:?}",
+                from, msg.source_version
+            ));
+            DeliveryOutcome::Rejected
+        }
+    }
+
+    /// Runs once a turn's delivery has been handled: logs how many actors
+    /// still have buffered messages behind an open gap. It only logs;
+    /// `Proc::repair_gaps` computes the open gaps itself from `pending`.
+    fn commit(&mut self, turn: &mut Turn) {
+        if !self.pending.is_empty() {
+            turn.log(format!(
+                "{} actor(s) still have buffered out-of-order messages pending repair",
+                self.pending.len()
+            ));
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -34,8 +429,17 @@ struct Proc {
     // Set of delivered (but not validated) transfers
     to_validate: Vec<(Identity, Msg)>,
 
-    // Operations that are causally related to the next operation on a given account
-    peers: HashSet<Identity>,
+    // Membership entity: highest-version PeerRecord per id, plus the expiry
+    // policy. Drives onboarding and account freezing.
+    membership: Membership,
+
+    // Anti-entropy entity: per-actor delivery log plus the out-of-order
+    // buffer, for gap repair.
+    anti_entropy: AntiEntropy,
+
+    // Number of times each Capability (keyed by its mint dot) has been
+    // spent, for enforcing Caveat::MaxUses deterministically.
+    cap_uses: HashMap<Dot<Identity>, u64>,
 }
 
 impl Proc {
@@ -46,71 +450,329 @@ impl Proc {
             seq: VClock::new(),
             rec: VClock::new(),
             to_validate: Vec::new(),
-            peers: HashSet::new(),
+            membership: Membership::default(),
+            anti_entropy: AntiEntropy::default(),
+            cap_uses: HashMap::new(),
         };
 
         proc.bank.onboard_account(id, initial_balance);
+        let mut turn = Turn::new();
+        proc.advertise(&mut turn, false);
+        turn.commit();
 
         proc
     }
 
-    fn onboard(&self) -> Vec<Cmd> {
-        vec![Cmd::BroadcastNewPeer {
-            new_peer: self.id,
+    /// Runs `f` as one turn: `f` queues effects (Cmds, log lines, deferred
+    /// state mutations) on the `Turn` it's given instead of touching
+    /// `rec`, `seq`, `to_validate`, `cap_uses` or the bank directly; the
+    /// membership and anti-entropy entities it drives update their own
+    /// state as they go. Once `f` returns, this is the only place
+    /// queued `Effect`s are actually applied, before the turn's Cmds and
+    /// log lines commit.
+    fn turn<F>(&mut self, f: F) -> Vec<Cmd>
+    where
+        F: FnOnce(&mut Self, &mut Turn),
+    {
+        let mut turn = Turn::new();
+        f(self, &mut turn);
+        let effects = mem::replace(&mut turn.effects, Vec::new());
+        for effect in effects {
+            self.apply_effect(effect);
+        }
+        turn.commit()
+    }
+
+    fn apply_effect(&mut self, effect: Effect) {
+        match effect {
+            Effect::ApplyRec(dot) => self.rec.apply(dot),
+            Effect::ApplySeq(dot) => self.seq.apply(dot),
+            Effect::RecordCapUse(minted_at) => {
+                *self.cap_uses.entry(minted_at).or_insert(0) += 1;
+            }
+            Effect::BankApply { account, op } => self.bank.apply(account, op),
+            Effect::RefreshLiveness { id, epoch } => self.membership.refresh_liveness(id, epoch),
+            Effect::OnboardAccount { id, initial_balance } => {
+                self.bank.onboard_account(id, initial_balance)
+            }
+            Effect::EnqueueForValidation { from, msg } => self.to_validate.push((from, msg)),
+        }
+    }
+
+    fn onboard(&mut self) -> Vec<Cmd> {
+        self.turn(|this, turn| {
+            let record = this.advertise(turn, false);
+            turn.send(Cmd::BroadcastPeerRecord { record });
+        })
+    }
+
+    /// Leaves the membership: bumps our own version with a tombstone so
+    /// other processes converge on removing us and freezing our account,
+    /// without requiring any particular delivery order.
+    fn leave(&mut self) -> Vec<Cmd> {
+        self.turn(|this, turn| {
+            let record = this.advertise(turn, true);
+            turn.send(Cmd::BroadcastPeerRecord { record });
+            this.membership.exit_hook(turn, this.id);
+        })
+    }
+
+    /// Bumps our membership version and merges the resulting record into
+    /// our own view before broadcasting it.
+    fn advertise(&mut self, turn: &mut Turn, tombstone: bool) -> PeerRecord {
+        let record = PeerRecord {
+            id: self.id,
             initial_balance: self.bank.initial_balance(self.id),
-        }]
+            version: self.membership.next_version(),
+            epoch: self.seq.dot(self.id).counter,
+            tombstone,
+        };
+        self.membership.message(
+            turn,
+            MembershipMsg {
+                record: record.clone(),
+            },
+        );
+        record
+    }
+
+    /// `agent` is the signing identity, used for causal ordering; `account`
+    /// is the balance being debited, which may belong to another agent if
+    /// `agent` holds delegated debit authority over it.
+    fn transfer(&self, agent: Identity, account: Account, to: Account, amount: Money) -> Vec<Cmd> {
+        assert_eq!(agent, self.id);
+        match self.bank.transfer(agent, account, to, amount) {
+            Some(transfer) => vec![Cmd::BroadcastMsg {
+                from: agent,
+                msg: Msg {
+                    account,
+                    op: Op::Transfer(transfer),
+                    source_version: self.seq.inc(agent),
+                    capability: None,
+                },
+            }],
+            None => vec![],
+        }
     }
 
-    fn transfer(&self, from: Identity, to: Identity, amount: Money) -> Vec<Cmd> {
-        assert_eq!(from, self.id);
-        match self.bank.transfer(from, to, amount) {
+    /// Like `transfer`, but spends a `Capability` minted by the account
+    /// owner instead of relying on `agent` owning, or holding a standing
+    /// Delegation over, `account`.
+    fn transfer_with_capability(
+        &self,
+        agent: Identity,
+        account: Account,
+        to: Account,
+        amount: Money,
+        capability: Capability,
+    ) -> Vec<Cmd> {
+        assert_eq!(agent, self.id);
+        assert_eq!(agent, capability.holder);
+        match self.bank.transfer(agent, account, to, amount) {
             Some(transfer) => vec![Cmd::BroadcastMsg {
-                from: from,
+                from: agent,
                 msg: Msg {
-                    op: transfer,
-                    source_version: self.seq.inc(from),
+                    account,
+                    op: Op::Transfer(transfer),
+                    source_version: self.seq.inc(agent),
+                    capability: Some(capability),
                 },
             }],
             None => vec![],
         }
     }
 
+    /// Mints a Capability letting `holder` submit transfers from `account`
+    /// restricted by `caveats`. Handed to `holder` out of band; `holder`
+    /// attaches it to the Msg when spending it.
+    fn mint_capability(&self, account: Account, holder: Identity, caveats: Vec<Caveat>) -> Capability {
+        Capability {
+            minted_at: self.seq.dot(self.id),
+            account,
+            holder,
+            caveats,
+        }
+    }
+
+    /// Grants `grantee` bounded debit authority over `account`, which must
+    /// be ours to delegate. The grant goes out as a causally-ordered Msg so
+    /// every correct process applies it at the same point in our history.
+    fn delegate(&self, account: Account, grantee: Identity, limit: Money) -> Vec<Cmd> {
+        vec![Cmd::BroadcastMsg {
+            from: self.id,
+            msg: Msg {
+                account,
+                op: Op::Delegate(Delegation {
+                    account,
+                    grantee,
+                    limit,
+                }),
+                source_version: self.seq.inc(self.id),
+                capability: None,
+            },
+        }]
+    }
+
+    /// Checks that `cap` actually grants `holder` authority over `account`,
+    /// then checks every caveat on it against deterministic, replicated
+    /// state so all correct processes agree on whether a capability-gated
+    /// transfer is still authorized.
+    fn check_caveats(&self, holder: Identity, account: Account, amount: Money, to: Account, cap: &Capability) -> bool {
+        if cap.holder != holder || cap.account != account {
+            return false;
+        }
+
+        // Provenance: a Capability is only as good as the standing
+        // authority of whoever minted it. Without this, a holder could
+        // mint itself a Capability naming any victim account and pass
+        // every caveat below despite never having been granted anything.
+        if !self.bank.is_authorized(cap.minted_at.actor, cap.account) {
+            return false;
+        }
+
+        let uses = *self.cap_uses.get(&cap.minted_at).unwrap_or(&0);
+        cap.caveats.iter().all(|caveat| match caveat {
+            Caveat::MaxAmount(max) => amount <= *max,
+            Caveat::ExpiresAt(expiry) => self.seq.dot(expiry.actor).counter < expiry.counter,
+            Caveat::RecipientAllowList(allowed) => allowed.contains(&to),
+            Caveat::MaxUses(max) => uses < *max,
+        })
+    }
+
     fn read(&self, account: Account) -> Money {
         self.bank.read(account)
     }
 
-    /// Executed when we successfully deliver messages to process p
-    fn on_delivery(&mut self, from: Identity, msg: Msg) {
+    /// Executed when we successfully deliver messages to process p. Hands
+    /// the delivery to the AntiEntropy entity, which decides whether it's
+    /// immediately acceptable, needs buffering, or is rejected; accepting
+    /// continues locally (not via self.rec, which won't reflect this
+    /// turn's deferred ApplyRec effects yet) so a run of buffered messages
+    /// that this delivery unblocks gets drained within the same turn.
+    fn on_delivery(&mut self, turn: &mut Turn, from: Identity, msg: Msg) {
         assert_eq!(from, msg.source_version.actor);
 
-        // Secure broadcast callback
-        if msg.source_version == self.rec.inc(from) {
-            println!(
-                "{} Accepted message from {} and enqueued for validation",
-                self.id, from
-            );
-            self.rec.apply(msg.source_version);
-            self.to_validate.push((from, msg));
-        } else {
-            println!(
-                "{} Rejected message from {}, transfer source version is invalid: {:?}",
-                self.id, from, msg.source_version
-            );
+        let expected_counter = self.rec.dot(from).counter + 1;
+        let outcome = self.anti_entropy.message(
+            turn,
+            AntiEntropyMsg {
+                from,
+                msg,
+                expected_counter,
+            },
+        );
+
+        match outcome {
+            DeliveryOutcome::Delivered(msg) => {
+                let mut next_counter = msg.source_version.counter;
+                self.accept(turn, from, msg);
+                loop {
+                    next_counter += 1;
+                    match self.anti_entropy.take_pending(from, next_counter) {
+                        Some(buffered) => {
+                            self.anti_entropy.record(from, buffered.clone());
+                            self.accept(turn, from, buffered);
+                        }
+                        None => break,
+                    }
+                }
+            }
+            DeliveryOutcome::Buffered {
+                from_counter,
+                to_counter,
+            } => {
+                turn.send(Cmd::RequestMissing {
+                    actor: from,
+                    from_counter,
+                    to_counter,
+                });
+            }
+            DeliveryOutcome::Rejected => {}
         }
+
+        self.anti_entropy.commit(turn);
+    }
+
+    /// Marks `msg` as received: defers applying it to `rec` and queuing it
+    /// for validation until turn end, so nothing here mutates `Proc` ahead
+    /// of the rest of the turn's effects.
+    fn accept(&mut self, turn: &mut Turn, from: Identity, msg: Msg) {
+        turn.defer(Effect::ApplyRec(msg.source_version));
+        turn.defer(Effect::EnqueueForValidation { from, msg });
     }
 
-    /// Executed when a transfer from `from` becomes valid.
-    fn on_validated(&mut self, from: Identity, msg: Msg) {
-        assert!(self.valid(from, &msg));
+    /// Anti-entropy sweep: re-requests any gap `AntiEntropy::gaps` still
+    /// reports open. The wired entrypoint for that gap computation — meant
+    /// for a caller (e.g. the net harness) to run periodically as a safety
+    /// net in case an earlier RequestMissing was itself dropped, rather
+    /// than leaving gap detection computed but never acted on.
+    fn repair_gaps(&mut self) -> Vec<Cmd> {
+        let rec = self.rec.clone();
+        self.turn(|this, turn| {
+            for (actor, (from_counter, to_counter)) in this.anti_entropy.gaps(&rec) {
+                turn.send(Cmd::RequestMissing {
+                    actor,
+                    from_counter,
+                    to_counter,
+                });
+            }
+        })
+    }
+
+    /// Answers an anti-entropy request: replies with the logged Msgs we hold
+    /// for `actor` in the requested counter range, re-broadcasting them so
+    /// the requester can replay its own causal chain forward.
+    fn handle_request_missing(&mut self, actor: Identity, from_counter: u64, to_counter: u64) -> Vec<Cmd> {
+        self.turn(|this, turn| {
+            for msg in this.anti_entropy.missing(actor, from_counter, to_counter) {
+                turn.send(Cmd::BroadcastMsg { from: actor, msg });
+            }
+        })
+    }
+
+    /// Executed when a transfer from `from` becomes valid. Defers every
+    /// state mutation instead of applying it inline, so it commits
+    /// alongside this turn's Cmds and log lines rather than ahead of them.
+    fn on_validated(&mut self, turn: &mut Turn, from: Identity, msg: Msg) {
+        assert!(self.validate(from, &msg));
         assert_eq!(msg.source_version, self.seq.inc(from));
 
         // TODO: rename Proc::seq to Proc::knowledge ala. VVwE
         // TODO: rename Proc::rec to Proc::forward_knowledge ala. VVwE
         // TODO: add test that "forward_knowleged >= knowledge" is invariant
-        self.seq.apply(msg.source_version);
+        turn.defer(Effect::ApplySeq(msg.source_version));
+
+        // Ordinary activity counts as liveness too, refreshing `from`'s
+        // membership epoch so it doesn't go stale from pure transfer
+        // volume with no re-advertisement.
+        turn.defer(Effect::RefreshLiveness {
+            id: from,
+            epoch: msg.source_version.counter,
+        });
+
+        // A capability only spends a use when it's actually what
+        // authorized this transfer — `validate` falls back to
+        // `bank.is_authorized` when the capability's caveats don't hold,
+        // and an owner may attach a capability to its own transfer
+        // redundantly, in neither of which cases did the capability do
+        // any work.
+        if let Some(cap) = &msg.capability {
+            let capability_authorized = match &msg.op {
+                Op::Transfer(transfer) => {
+                    self.check_caveats(from, msg.account, transfer.amount, transfer.to, cap)
+                }
+                Op::Delegate(_) => false,
+            };
+            if capability_authorized {
+                turn.defer(Effect::RecordCapUse(cap.minted_at));
+            }
+        }
 
         // Finally, apply the operation to the underlying algorithm
-        self.bank.apply(msg.op);
+        turn.defer(Effect::BankApply {
+            account: msg.account,
+            op: msg.op,
+        });
     }
 
     fn validate(&self, from: Identity, msg: &Msg) -> bool {
@@ -126,45 +788,250 @@ impl Proc {
                 msg.source_version, from, self.seq.dot(from)
             );
             false
+        } else if !self.membership.is_member(from) {
+            println!(
+                "[INVALID] {} is not a current member (left or never onboarded)",
+                from
+            );
+            false
+        } else if self.membership.is_expired(from, self.seq.dot(from).counter) {
+            println!(
+                "[INVALID] {}'s membership record is stale, account frozen for new debits",
+                from
+            );
+            false
         } else {
-            // Finally, check with the underlying algorithm
-            self.bank.validate(from, &msg.op)
+            // A Capability stands in for `from`'s own ownership/delegation
+            // only when it was actually minted by someone who had standing
+            // authority over the account — `check_caveats` verifies that
+            // provenance itself, so a forged or mis-targeted Capability
+            // falls through to `bank.is_authorized` and fails like any
+            // other unauthorized `from`.
+            let authorized = match (&msg.op, &msg.capability) {
+                (Op::Transfer(transfer), Some(cap)) => {
+                    self.check_caveats(from, msg.account, transfer.amount, transfer.to, cap)
+                        || self.bank.is_authorized(from, msg.account)
+                }
+                _ => self.bank.is_authorized(from, msg.account),
+            };
+
+            if !authorized {
+                println!(
+                    "[INVALID] {} is not authorized to act on account {:?}",
+                    from, msg.account
+                );
+                false
+            } else {
+                // Finally, check with the underlying algorithm
+                match &msg.op {
+                    Op::Transfer(transfer) => self.bank.validate(from, msg.account, transfer),
+                    Op::Delegate(delegation) if delegation.account != msg.account => {
+                        // `Delegation::account` is redundant with `Msg::account` in
+                        // every honest message (`delegate` sets both from the same
+                        // value), but nothing stops a Byzantine sender from
+                        // broadcasting a Msg/Delegation pair that disagrees on
+                        // which account the grant applies to — reject it like any
+                        // other malformed message rather than trusting whichever
+                        // field `validate_delegation` happens to read.
+                        println!(
+                            "[INVALID] Delegation account {:?} does not match msg account {:?}",
+                            delegation.account, msg.account
+                        );
+                        false
+                    }
+                    Op::Delegate(delegation) => self.bank.validate_delegation(from, delegation),
+                }
+            }
         }
     }
 
-    fn handle_new_peer(&mut self, new_proc: Identity, initial_balance: Money) -> Vec<Cmd> {
-        if !self.peers.contains(&new_proc) {
-            // this is a new peer
-            self.peers.insert(new_proc);
-            self.bank.onboard_account(new_proc, initial_balance);
+    /// Handles an incoming membership advertisement, merging it into our
+    /// CRDT view and letting that merge — not the raw broadcast — decide
+    /// whether to onboard the account. Runs as one turn.
+    ///
+    /// Onboarding and the reply broadcast only fire the first time we learn
+    /// of `record.id`: a member's periodic liveness refresh also advances
+    /// its version and so also passes the merge, but must not re-trigger
+    /// onboarding or another round of `BroadcastPeerRecord` — otherwise
+    /// every refresh would bounce a reply back to the refresher, which
+    /// would bump its own version again to merge it, forever.
+    fn handle_peer_record(&mut self, record: PeerRecord) -> Vec<Cmd> {
+        self.turn(|this, turn| {
+            let previously_known = this.membership.get(record.id).is_some();
+            let msg = MembershipMsg {
+                record: record.clone(),
+            };
+            if !this.membership.message(turn, msg) {
+                // Stale or already-known advertisement: CRDT merge is a no-op.
+                return;
+            }
 
-            // broadcast this proc so that the new peer will discover initial balances
-            // TODO: broadcast here is a bit overkill, just need a direct 1-1
-            //       communication with the new proc.
-            vec![Cmd::BroadcastNewPeer {
-                new_peer: self.id,
-                initial_balance: self.bank.initial_balance(self.id),
-            }]
-        } else {
-            // We already have this peer, do nothing
-            vec![]
-        }
+            if record.tombstone {
+                this.membership.exit_hook(turn, record.id);
+            } else if record.id != this.id && !previously_known {
+                // First time we've seen this peer: onboard its account and
+                // let it discover ours in return.
+                turn.defer(Effect::OnboardAccount {
+                    id: record.id,
+                    initial_balance: record.initial_balance,
+                });
+
+                // broadcast this proc so that the new peer will discover initial balances
+                // TODO: broadcast here is a bit overkill, just need a direct 1-1
+                //       communication with the new proc.
+                let our_record = this.advertise(turn, false);
+                turn.send(Cmd::BroadcastPeerRecord { record: our_record });
+            }
+
+            this.membership.commit(turn);
+        })
     }
 
+    /// Runs one incoming Msg's delivery as its own turn, then lets
+    /// `process_msg_queue` schedule whatever became deliverable.
     fn handle_msg(&mut self, from: Identity, msg: Msg) -> Vec<Cmd> {
-        self.on_delivery(from, msg);
-        self.process_msg_queue();
-        vec![]
+        let mut cmds = self.turn(|this, turn| this.on_delivery(turn, from, msg));
+        cmds.extend(self.process_msg_queue());
+        cmds
     }
 
-    fn process_msg_queue(&mut self) {
+    /// Deterministic scheduler: runs every message waiting for validation as
+    /// its own turn, so each one's deferred effects (seq/bank mutation)
+    /// commit before the next message is validated against them, and a
+    /// turn's outbound Cmds are never split across two validations.
+    fn process_msg_queue(&mut self) -> Vec<Cmd> {
         let to_validate = mem::replace(&mut self.to_validate, Vec::new());
+        let mut cmds = Vec::new();
         for (to, msg) in to_validate {
-            if self.valid(to, &msg) {
-                self.on_validated(to, msg);
-            } else {
-                println!("[DROP] invalid message detected {:?}", (to, msg));
-            }
+            cmds.extend(self.turn(|this, turn| {
+                if this.validate(to, &msg) {
+                    this.on_validated(turn, to, msg);
+                } else {
+                    turn.log(format!("[DROP] invalid message detected {:?}", (to, msg)));
+                }
+            }));
         }
+        cmds
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Identity/Account/Money are Bank's types (bank.rs), treated here as
+    // plain integers consistent with how this file already uses them.
+    const ALICE: Identity = 1;
+    const BOB: Identity = 2;
+
+    /// Pulls the Msg back out of whatever a transfer/delegate call queued,
+    /// for tests that need to hand-deliver it rather than go through a net.
+    fn broadcast_msg(cmds: Vec<Cmd>) -> Msg {
+        cmds.into_iter()
+            .find_map(|cmd| match cmd {
+                Cmd::BroadcastMsg { msg, .. } => Some(msg),
+                _ => None,
+            })
+            .expect("expected a BroadcastMsg among the queued Cmds")
+    }
+
+    /// Out-of-order delivery must not apply a message ahead of its causal
+    /// predecessor; once the predecessor arrives, both must replay and
+    /// apply in order within the same turn-driven scheduler pass — the
+    /// determinism `process_msg_queue`'s per-message turns are meant to
+    /// guarantee regardless of delivery order.
+    #[test]
+    fn replays_buffered_messages_in_causal_order_once_the_gap_closes() {
+        let mut bob = Proc::new(BOB, 100);
+        bob.handle_peer_record(PeerRecord {
+            id: ALICE,
+            initial_balance: 100,
+            version: 1,
+            epoch: 0,
+            tombstone: false,
+        });
+
+        let mut alice = Proc::new(ALICE, 100);
+        let first_msg = broadcast_msg(alice.transfer(ALICE, ALICE, BOB, 10));
+        // Self-deliver, exactly as a real net layer would loop our own
+        // broadcast back to us, so the second transfer's source_version is
+        // a genuine causal successor of the first rather than a duplicate.
+        alice.handle_msg(ALICE, first_msg.clone());
+        let second_msg = broadcast_msg(alice.transfer(ALICE, ALICE, BOB, 10));
+
+        // Deliver #2 first: it must be buffered rather than applied, and
+        // bob must ask for the missing predecessor instead of dropping it.
+        let cmds = bob.handle_msg(ALICE, second_msg);
+        assert!(cmds
+            .iter()
+            .any(|cmd| matches!(cmd, Cmd::RequestMissing { .. })));
+        assert_eq!(bob.read(BOB), 100);
+
+        // Delivering #1 now must apply both #1 and #2, in that order,
+        // within the same handle_msg call's scheduler pass.
+        bob.handle_msg(ALICE, first_msg);
+        assert_eq!(bob.read(BOB), 120);
+    }
+
+    /// A member must not go stale purely from its own continued activity:
+    /// each validated transfer refreshes its own liveness epoch, so crossing
+    /// MEMBERSHIP_EXPIRY_EPOCHS worth of transfers with no explicit
+    /// re-advertisement must not start getting them rejected.
+    #[test]
+    fn ordinary_activity_keeps_a_member_from_expiring() {
+        let mut bob = Proc::new(BOB, 1000);
+        bob.handle_peer_record(PeerRecord {
+            id: ALICE,
+            initial_balance: 1000,
+            version: 1,
+            epoch: 0,
+            tombstone: false,
+        });
+
+        let mut alice = Proc::new(ALICE, 1000);
+        let transfers = MEMBERSHIP_EXPIRY_EPOCHS + 10;
+        for _ in 0..transfers {
+            let msg = broadcast_msg(alice.transfer(ALICE, ALICE, BOB, 1));
+            alice.handle_msg(ALICE, msg.clone());
+            bob.handle_msg(ALICE, msg);
+        }
+
+        assert_eq!(bob.read(BOB), 1000 + transfers);
+    }
+
+    /// A grantee with a standing Delegation must be able to debit the
+    /// delegator's account directly — exercising the path where `Msg::account`
+    /// (what `bank.is_authorized` checks) and `Delegation::account` (what
+    /// `bank.validate_delegation` records) must agree for the grant to take
+    /// effect.
+    #[test]
+    fn delegated_debit_is_accepted_once_the_delegation_applies() {
+        let mut alice = Proc::new(ALICE, 1000);
+        let mut bob = Proc::new(BOB, 1000);
+        alice.handle_peer_record(PeerRecord {
+            id: BOB,
+            initial_balance: 1000,
+            version: 1,
+            epoch: 0,
+            tombstone: false,
+        });
+        bob.handle_peer_record(PeerRecord {
+            id: ALICE,
+            initial_balance: 1000,
+            version: 1,
+            epoch: 0,
+            tombstone: false,
+        });
+
+        let delegation_msg = broadcast_msg(alice.delegate(ALICE, BOB, 500));
+        alice.handle_msg(ALICE, delegation_msg.clone());
+        bob.handle_msg(ALICE, delegation_msg);
+
+        let debit_msg = broadcast_msg(bob.transfer(BOB, ALICE, BOB, 100));
+        alice.handle_msg(BOB, debit_msg.clone());
+        bob.handle_msg(BOB, debit_msg);
+
+        assert_eq!(alice.read(ALICE), 900);
+        assert_eq!(bob.read(BOB), 1100);
     }
 }